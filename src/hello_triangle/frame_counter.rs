@@ -1,3 +1,6 @@
+// Number of past per-frame durations kept for percentile queries.
+const HISTORY_CAPACITY: usize = 256;
+
 pub struct FrameCounter {
     // Instant of the last time we printed the frame time.
     last_printed_instant: web_time::Instant,
@@ -5,6 +8,13 @@ pub struct FrameCounter {
     frame_count: u32,
     last_fps: f32,
     last_frame_time: f32,
+
+    // Ring buffer of the last `HISTORY_CAPACITY` per-frame durations (ms),
+    // used to surface spikes that the once-per-second average hides.
+    last_frame_instant: web_time::Instant,
+    frame_time_history: [f32; HISTORY_CAPACITY],
+    history_len: usize,
+    history_next: usize,
 }
 
 impl FrameCounter {
@@ -14,20 +24,30 @@ impl FrameCounter {
             frame_count: 0,
             last_fps: 0.0,
             last_frame_time: 0.0,
+            last_frame_instant: web_time::Instant::now(),
+            frame_time_history: [0.0; HISTORY_CAPACITY],
+            history_len: 0,
+            history_next: 0,
         }
     }
 
     pub fn update(&mut self) {
+        let now = web_time::Instant::now();
+        let frame_time_ms = (now - self.last_frame_instant).as_secs_f32() * 1000.0;
+        self.last_frame_instant = now;
+        self.frame_time_history[self.history_next] = frame_time_ms;
+        self.history_next = (self.history_next + 1) % HISTORY_CAPACITY;
+        self.history_len = (self.history_len + 1).min(HISTORY_CAPACITY);
+
         self.frame_count += 1;
-        let new_instant = web_time::Instant::now();
-        let elapsed_secs = (new_instant - self.last_printed_instant).as_secs_f32();
+        let elapsed_secs = (now - self.last_printed_instant).as_secs_f32();
         if elapsed_secs > 1.0 {
             let elapsed_ms = elapsed_secs * 1000.0;
             let frame_time = elapsed_ms / self.frame_count as f32;
             let fps = self.frame_count as f32 / elapsed_secs;
             //log::info!("Frame time {:.2}ms ({:.1} FPS)", frame_time, fps);
 
-            self.last_printed_instant = new_instant;
+            self.last_printed_instant = now;
             self.frame_count = 0;
             self.last_fps = fps;
             self.last_frame_time = frame_time;
@@ -41,4 +61,27 @@ impl FrameCounter {
     pub fn get_last_frame_time(&self) -> f32 {
         self.last_frame_time
     }
-}
\ No newline at end of file
+
+    /// Average frame time (ms) over the ring buffer, i.e. over the last
+    /// `HISTORY_CAPACITY` frames rather than the last whole second.
+    pub fn get_rolling_average_frame_time(&self) -> f32 {
+        if self.history_len == 0 {
+            return 0.0;
+        }
+        let sum: f32 = self.frame_time_history[..self.history_len].iter().sum();
+        sum / self.history_len as f32
+    }
+
+    /// Frame time (ms) at percentile `p` (e.g. `0.99` for the 1% worst
+    /// frames, `0.999` for the 0.1% worst) over the ring buffer. Returns
+    /// 0.0 if no frames have been recorded yet.
+    pub fn get_percentile_frame_time(&self, p: f32) -> f32 {
+        if self.history_len == 0 {
+            return 0.0;
+        }
+        let mut scratch: Vec<f32> = self.frame_time_history[..self.history_len].to_vec();
+        scratch.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let index = ((self.history_len - 1) as f32 * p).round() as usize;
+        scratch[index]
+    }
+}
@@ -1,50 +1,129 @@
-use std::{borrow::Cow, mem};
+use std::borrow::Cow;
 use std::sync::Arc;
 use web_time::{Duration, Instant};
 use winit::{
-    event::{Event, WindowEvent},
-    event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget},
-    window::Window,
+    application::ApplicationHandler,
+    event::WindowEvent,
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+    window::{Window, WindowAttributes, WindowId},
 };
 mod frame_counter;
 use frame_counter::FrameCounter;
 
+/// Window-chrome options that need to agree with each other to avoid a
+/// visible seam between the title bar and the rendered content.
+///
+/// A transparent title bar on macOS only looks right if the surface's clear
+/// color matches the color painted behind it, so the two are bundled here
+/// rather than configured independently.
+#[derive(Clone, Copy)]
+pub struct WindowChrome {
+    /// Paint the title bar transparently and extend content under it.
+    /// Only has an effect on macOS; ignored elsewhere.
+    pub transparent_titlebar: bool,
+    /// Clear color used for `render_frame`'s `LoadOp::Clear`.
+    pub clear_color: wgpu::Color,
+}
+
+impl Default for WindowChrome {
+    fn default() -> Self {
+        Self {
+            transparent_titlebar: false,
+            clear_color: wgpu::Color::GREEN,
+        }
+    }
+}
+
+/// Frame-pacing preferences: which present mode to request from the surface,
+/// and an optional software frame-rate cap on top of it.
+///
+/// `present_mode` governs how the compositor paces presentation (`Fifo` for
+/// vsync, `Mailbox` for low-latency triple buffering, `Immediate` for
+/// uncapped). `target_fps` additionally throttles `RedrawRequested` via
+/// `ControlFlow::WaitUntil` when the chosen present mode doesn't already cap
+/// the frame rate on its own (i.e. anything but `Fifo`).
+#[derive(Clone, Copy)]
+pub struct FramePacing {
+    pub present_mode: wgpu::PresentMode,
+    pub target_fps: Option<u32>,
+}
+
+impl Default for FramePacing {
+    fn default() -> Self {
+        Self {
+            present_mode: wgpu::PresentMode::Fifo,
+            target_fps: None,
+        }
+    }
+}
+
+/// Builds the `WindowAttributes` shared by every platform, applying the
+/// macOS transparent-titlebar chrome and (on the web) attaching to the
+/// canvas already present in the host page.
+fn build_window_attributes(chrome: &WindowChrome) -> WindowAttributes {
+    #[allow(unused_mut)]
+    let mut attributes = Window::default_attributes();
+    #[cfg(target_arch = "wasm32")]
+    {
+        use wasm_bindgen::JsCast;
+        use winit::platform::web::WindowAttributesExtWebSys;
+        let canvas = web_sys::window()
+            .unwrap()
+            .document()
+            .unwrap()
+            .get_element_by_id("canvas")
+            .unwrap()
+            .dyn_into::<web_sys::HtmlCanvasElement>()
+            .unwrap();
+        attributes = attributes.with_canvas(Some(canvas));
+    }
+    #[cfg(target_os = "macos")]
+    if chrome.transparent_titlebar {
+        use winit::platform::macos::WindowAttributesExtMacOS;
+        attributes = attributes
+            .with_titlebar_transparent(true)
+            .with_fullsize_content_view(true);
+    }
+    attributes
+}
+
+/// Reads the canvas's CSS size and `devicePixelRatio`, resizes its backing
+/// buffer to match, and returns the resulting physical size.
+///
+/// Called both for the initial `SurfaceConfiguration` and on every
+/// `Resized` so the triangle stays crisp (rather than blurry or
+/// letterboxed) on HiDPI displays as the page is resized.
+#[cfg(target_arch = "wasm32")]
+fn sync_canvas_size(window: &Window) -> winit::dpi::PhysicalSize<u32> {
+    use winit::platform::web::WindowExtWebSys;
+    let canvas = window.canvas().expect("window has no canvas");
+    let dpr = web_sys::window().unwrap().device_pixel_ratio();
+    let width = ((canvas.client_width() as f64 * dpr).round().max(1.0)) as u32;
+    let height = ((canvas.client_height() as f64 * dpr).round().max(1.0)) as u32;
+    canvas.set_width(width);
+    canvas.set_height(height);
+    winit::dpi::PhysicalSize::new(width, height)
+}
+
 struct EventLoopWrapper {
     event_loop: EventLoop<()>,
-    window: Arc<Window>,
 }
 
 impl EventLoopWrapper {
     pub fn new() -> Self {
-        let event_loop = EventLoop::new().unwrap();
-        #[allow(unused_mut)]
-        let mut builder = winit::window::WindowBuilder::new();
-        #[cfg(target_arch = "wasm32")]
-        {
-            use wasm_bindgen::JsCast;
-            use winit::platform::web::WindowBuilderExtWebSys;
-            let canvas = web_sys::window()
-                .unwrap()
-                .document()
-                .unwrap()
-                .get_element_by_id("canvas")
-                .unwrap()
-                .dyn_into::<web_sys::HtmlCanvasElement>()
-                .unwrap();
-            builder = builder.with_canvas(Some(canvas));
-        }
-        let window = Arc::new(builder.build(&event_loop).unwrap());
-
         Self {
-            event_loop,
-            window,
+            event_loop: EventLoop::new().unwrap(),
         }
-    }    
+    }
 }
 
-struct Framework<'a> {
+struct Framework {
+    window: Arc<Window>,
     frame_counter: FrameCounter,
-    surface: wgpu::Surface<'a>,
+    // `None` while the native window is unavailable, e.g. between Android's
+    // `Suspended` and `Resumed` lifecycle events. The adapter/device/queue
+    // stay alive across that gap since only the surface is platform-bound.
+    surface: Option<wgpu::Surface<'static>>,
     adapter: wgpu::Adapter,
     device: wgpu::Device,
     queue: wgpu::Queue,
@@ -53,17 +132,23 @@ struct Framework<'a> {
     instance: wgpu::Instance,
     render_pipeline: wgpu::RenderPipeline,
     pipeline_layout: wgpu::PipelineLayout,
+    clear_color: wgpu::Color,
+    target_fps: Option<u32>,
+    last_frame_time: Instant,
 }
 
-impl Framework<'_> {
-    pub async fn new(event_loop_wrapper: &EventLoopWrapper) -> Self {
-        let mut size = event_loop_wrapper.window.inner_size();
+impl Framework {
+    pub async fn new(window: &Arc<Window>, chrome: &WindowChrome, pacing: &FramePacing) -> Self {
+        #[cfg(target_arch = "wasm32")]
+        let mut size = sync_canvas_size(window);
+        #[cfg(not(target_arch = "wasm32"))]
+        let mut size = window.inner_size();
         size.width = size.width.max(1);
         size.height = size.height.max(1);
 
         let instance = wgpu::Instance::default();
 
-        let surface = instance.create_surface(Arc::clone(&event_loop_wrapper.window)).unwrap();
+        let surface = instance.create_surface(Arc::clone(window)).unwrap();
 
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
@@ -126,16 +211,25 @@ impl Framework<'_> {
             multiview: None,
         });
 
-        let config = surface
+        let mut config = surface
             .get_default_config(&adapter, size.width, size.height)
             .unwrap();
+        // Fall back to `Fifo` (always supported) if the requested present
+        // mode isn't one of the surface's supported modes.
+        config.present_mode = if swapchain_capabilities
+            .present_modes
+            .contains(&pacing.present_mode)
+        {
+            pacing.present_mode
+        } else {
+            wgpu::PresentMode::Fifo
+        };
         surface.configure(&device, &config);
 
-        //let window = std::mem::take(&mut self.window);
-
         Self {
+            window: Arc::clone(window),
             frame_counter: FrameCounter::new(),
-            surface,
+            surface: Some(surface),
             adapter,
             device,
             queue,
@@ -144,54 +238,63 @@ impl Framework<'_> {
             instance,
             render_pipeline,
             pipeline_layout,
+            clear_color: chrome.clear_color,
+            target_fps: pacing.target_fps,
+            last_frame_time: Instant::now(),
         }
     }
-    pub async fn run_loop(&mut self, event_loop_wrapper: EventLoopWrapper) {
-
-        const FPS: u64 = 30;
-        const FRAME_DURATION: Duration = Duration::from_millis(1000 / FPS); 
-        let mut last_frame_time = Instant::now();
-
-        event_loop_wrapper.event_loop.run(move |event: Event<()>, target: &EventLoopWindowTarget<()>| {
-                if let Event::WindowEvent {
-                    window_id: _,
-                    event,
-                } = event
-                {
-                    match event {
-                        WindowEvent::Resized(new_size) => {
-                            // Reconfigure the surface with the new size
-                            self.config.width = new_size.width.max(1);
-                            self.config.height = new_size.height.max(1);
-                            self.surface.configure(&self.device, &self.config);
-                            // On macos the window needs to be redrawn manually after resizing
-                            event_loop_wrapper.window.request_redraw();
-                        }
-                        WindowEvent::RedrawRequested => {
-
-                            // set to window title.
-                            let title = format!("FPS: {:.1}", self.frame_counter.get_last_fps());
-                            event_loop_wrapper.window.set_title(title.as_str());
-
-                            // Calculate when the next frame should be
-                            let now = Instant::now();
-                            let duration = now.duration_since(last_frame_time);
-                            if duration >= FRAME_DURATION {
-                                last_frame_time = now;
-                                self.render_frame();
-                            }
-
-                            event_loop_wrapper.window.request_redraw();
-                        }
-                        WindowEvent::CloseRequested => target.exit(),
-                        _ => {}
-                    };
-                }
-            })
-            .unwrap();
+
+    /// (Re)creates the surface from a freshly-available native window and
+    /// reconfigures it with the stored `SurfaceConfiguration`.
+    ///
+    /// On Android the native window is torn down whenever the activity is
+    /// suspended, which invalidates the `wgpu::Surface` built on top of it.
+    /// This is called from `resumed` to rebuild the surface after a suspend;
+    /// on first start-up `Framework::new` creates the surface itself.
+    pub fn resume(&mut self, window: &Arc<Window>) {
+        let surface = self
+            .instance
+            .create_surface(Arc::clone(window))
+            .expect("Failed to create surface");
+        surface.configure(&self.device, &self.config);
+        self.window = Arc::clone(window);
+        self.surface = Some(surface);
+    }
+
+    /// Drops the surface in response to `suspended`. The native window is no
+    /// longer valid on Android once this fires, so the surface can't be.
+    fn suspend(&mut self) {
+        self.surface = None;
+    }
+
+    /// How long to wait between frames under a software cap, or `None` to
+    /// redraw as fast as the present mode allows.
+    ///
+    /// `Fifo` already paces presentation to vsync, so a software cap is only
+    /// meaningful on top of `Mailbox`/`Immediate`.
+    fn target_frame_duration(&self) -> Option<Duration> {
+        if self.config.present_mode == wgpu::PresentMode::Fifo {
+            return None;
+        }
+        self.target_fps
+            .map(|fps| Duration::from_secs_f64(1.0 / fps as f64))
+    }
+
+    fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        self.config.width = new_size.width.max(1);
+        self.config.height = new_size.height.max(1);
+        if let Some(surface) = &self.surface {
+            surface.configure(&self.device, &self.config);
+        }
     }
+
     fn render_frame(&mut self) {
-        let frame = self.surface
+        // The surface is briefly unavailable between Android's `Suspended`
+        // and `Resumed` events; just skip the frame rather than panicking.
+        let Some(surface) = &self.surface else {
+            return;
+        };
+        let frame = surface
                         .get_current_texture()
                         .expect("Failed to acquire next swap chain texture");
         let view = frame
@@ -209,7 +312,7 @@ impl Framework<'_> {
                         view: &view,
                         resolve_target: None,
                         ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(wgpu::Color::GREEN),
+                            load: wgpu::LoadOp::Clear(self.clear_color),
                             store: wgpu::StoreOp::Store,
                         },
                     })],
@@ -228,19 +331,173 @@ impl Framework<'_> {
     }
 }
 
-pub async fn main() {
-    let event_loop_wrapper = EventLoopWrapper::new();
-    let mut framework = Framework::new(&event_loop_wrapper).await;
+/// Top-level `ApplicationHandler` state.
+///
+/// The window and GPU state can't be created until winit hands us an
+/// `ActiveEventLoop` in `resumed`, so we start out `Uninitialized` and only
+/// hold a `Framework` once that has happened. This is also what lets the
+/// same code run on Android, where the window doesn't exist yet when the
+/// app starts and can disappear and reappear across suspend/resume.
+enum App {
+    Uninitialized {
+        chrome: WindowChrome,
+        pacing: FramePacing,
+    },
+    /// Web only: the window exists but `Framework::new` is still running on
+    /// the microtask queue. `request_adapter`/`request_device` are async and
+    /// can't be blocked on in the browser, so `resumed` kicks the future off
+    /// via `spawn_local` and this slot is filled in once it resolves; see
+    /// `about_to_wait`, which promotes it to `Initialized`.
+    #[cfg(target_arch = "wasm32")]
+    Initializing(std::rc::Rc<std::cell::RefCell<Option<Framework>>>),
+    Initialized(Framework),
+}
 
-    #[cfg(not(target_arch = "wasm32"))]
-    {
-        env_logger::init();
-        pollster::block_on(framework.run_loop(event_loop_wrapper));
+impl ApplicationHandler for App {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if let App::Initialized(framework) = self {
+            let window = Arc::clone(&framework.window);
+            framework.resume(&window);
+            framework.window.request_redraw();
+            return;
+        }
+        let App::Uninitialized { chrome, pacing } = self else {
+            return;
+        };
+        let chrome = *chrome;
+        let pacing = *pacing;
+
+        let attributes = build_window_attributes(&chrome);
+        let window = Arc::new(
+            event_loop
+                .create_window(attributes)
+                .expect("Failed to create window"),
+        );
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let framework = pollster::block_on(Framework::new(&window, &chrome, &pacing));
+            window.request_redraw();
+            *self = App::Initialized(framework);
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            let slot = std::rc::Rc::new(std::cell::RefCell::new(None));
+            let slot_for_future = std::rc::Rc::clone(&slot);
+            wasm_bindgen_futures::spawn_local(async move {
+                let framework = Framework::new(&window, &chrome, &pacing).await;
+                window.request_redraw();
+                *slot_for_future.borrow_mut() = Some(framework);
+            });
+            *self = App::Initializing(slot);
+        }
     }
+
+    fn suspended(&mut self, _event_loop: &ActiveEventLoop) {
+        if let App::Initialized(framework) = self {
+            framework.suspend();
+        }
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        window_id: WindowId,
+        event: WindowEvent,
+    ) {
+        let App::Initialized(framework) = self else {
+            return;
+        };
+        if framework.window.id() != window_id {
+            return;
+        }
+        match event {
+            WindowEvent::Resized(new_size) => {
+                // On the web, winit's reported size can lag a CSS resize or
+                // a devicePixelRatio change (e.g. dragging between
+                // displays), so re-derive it from the canvas directly.
+                #[cfg(target_arch = "wasm32")]
+                let new_size = sync_canvas_size(&framework.window);
+                framework.resize(new_size);
+                // On macos the window needs to be redrawn manually after resizing
+                framework.window.request_redraw();
+            }
+            WindowEvent::RedrawRequested => {
+                // set to window title, including the 0.1% worst frame time
+                // so stutter shows up immediately instead of being smoothed
+                // away by the once-per-second average.
+                let title = format!(
+                    "FPS: {:.1} | worst 0.1%: {:.2}ms",
+                    framework.frame_counter.get_last_fps(),
+                    framework.frame_counter.get_percentile_frame_time(0.999),
+                );
+                framework.window.set_title(title.as_str());
+
+                framework.last_frame_time = Instant::now();
+                framework.render_frame();
+            }
+            WindowEvent::CloseRequested => event_loop.exit(),
+            _ => {}
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        #[cfg(target_arch = "wasm32")]
+        if let App::Initializing(slot) = self {
+            match slot.borrow_mut().take() {
+                Some(framework) => *self = App::Initialized(framework),
+                // Still waiting on the adapter/device; nothing to redraw yet.
+                None => return,
+            }
+        }
+        let App::Initialized(framework) = self else {
+            return;
+        };
+        match framework.target_frame_duration() {
+            // Under a software cap, don't spin: park the loop until the
+            // next frame is due instead of requesting a redraw on every
+            // iteration.
+            Some(target_frame_duration) => {
+                let next_frame_time = framework.last_frame_time + target_frame_duration;
+                if Instant::now() >= next_frame_time {
+                    framework.window.request_redraw();
+                } else {
+                    event_loop.set_control_flow(ControlFlow::WaitUntil(next_frame_time));
+                }
+            }
+            // Vsync (or genuinely uncapped) paces itself; keep redrawing as
+            // fast as the compositor lets us.
+            None => {
+                event_loop.set_control_flow(ControlFlow::Poll);
+                framework.window.request_redraw();
+            }
+        }
+    }
+}
+
+pub async fn main() {
+    #[cfg(not(target_arch = "wasm32"))]
+    env_logger::init();
     #[cfg(target_arch = "wasm32")]
     {
         std::panic::set_hook(Box::new(console_error_panic_hook::hook));
         console_log::init().expect("could not initialize logger");
-        wasm_bindgen_futures::spawn_local(framework.run());
+    }
+
+    let chrome = WindowChrome {
+        transparent_titlebar: true,
+        ..Default::default()
+    };
+    let pacing = FramePacing::default();
+    let event_loop_wrapper = EventLoopWrapper::new();
+    #[allow(unused_mut)]
+    let mut app = App::Uninitialized { chrome, pacing };
+
+    #[cfg(not(target_arch = "wasm32"))]
+    event_loop_wrapper.event_loop.run_app(&mut app).unwrap();
+    #[cfg(target_arch = "wasm32")]
+    {
+        use winit::platform::web::EventLoopExtWebSys;
+        event_loop_wrapper.event_loop.spawn_app(app);
     }
 }